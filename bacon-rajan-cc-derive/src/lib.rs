@@ -0,0 +1,318 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! `#[derive(Trace)]` for `bacon-rajan-cc`.
+//!
+//! Hand-writing `Trace` impls is error-prone: forgetting to call the
+//! tracer on one field silently leaks cycles, and nobody notices until
+//! memory grows unbounded. This derive generates `fn trace` by invoking
+//! `.trace(tracer)` on every field of a struct, or every bound field of
+//! whichever enum variant is live, and sets `is_atomic()` to `true`
+//! automatically when every traced field is itself atomic.
+//!
+//! Use `#[trace(ignore)]` on a field to skip a non-owning edge, such as a
+//! back-pointer that the owning direction already accounts for -- tracing
+//! it too would double-count the edge. Use `#[trace(atomic)]` on the type
+//! itself to force `is_atomic()` to `true` regardless of the fields, for
+//! types that manage their own atomicity.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use syn::{Data, DeriveInput, Fields, Index, Meta, NestedMeta};
+
+#[proc_macro_derive(Trace, attributes(trace))]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let mut input: DeriveInput = syn::parse(input).expect("#[derive(Trace)] expects a valid item");
+    let name = input.ident.clone();
+
+    let force_atomic = has_atomic_attr(&input.attrs);
+
+    add_trace_bounds(&mut input.generics, &input.data);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (trace_body, is_atomic_body) = match &input.data {
+        Data::Struct(data) => fields_trace(&data.fields, force_atomic),
+        Data::Enum(data) => {
+            let mut trace_arms = Vec::new();
+            let mut atomic_arms = Vec::new();
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let (pattern, trace_stmts, atomic_expr) =
+                    variant_trace(variant_ident, &variant.fields, force_atomic);
+                trace_arms.push(quote! { #name::#pattern => { #(#trace_stmts)* } });
+                atomic_arms.push(quote! { #name::#pattern => #atomic_expr });
+            }
+            (
+                quote! { match *self { #(#trace_arms)* } },
+                quote! { match *self { #(#atomic_arms,)* } },
+            )
+        }
+        Data::Union(_) => panic!("#[derive(Trace)] does not support unions"),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics Trace for #name #ty_generics #where_clause {
+            fn trace(&self, tracer: &mut Tracer) {
+                #trace_body
+            }
+
+            fn is_atomic(&self) -> bool {
+                #is_atomic_body
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+/// Returns the bare words (such as `ignore` or `atomic`) inside every
+/// `#[trace(...)]` attribute attached to an item or field, parsed rather
+/// than compared as tokens. Anything other than a bare word is skipped.
+fn trace_meta_words(attrs: &[syn::Attribute]) -> Vec<syn::Ident> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("trace"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list),
+            _ => None,
+        })
+        .flat_map(|list| list.nested.into_iter())
+        .filter_map(|nested| match nested {
+            NestedMeta::Meta(Meta::Path(path)) => path.get_ident().cloned(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn has_atomic_attr(attrs: &[syn::Attribute]) -> bool {
+    trace_meta_words(attrs).iter().any(|word| word == "atomic")
+}
+
+fn has_ignore_attr(attrs: &[syn::Attribute]) -> bool {
+    trace_meta_words(attrs).iter().any(|word| word == "ignore")
+}
+
+/// Adds a `FieldType: Trace` bound to `generics`'s where-clause for every
+/// non-ignored field type in `data`, so the generated impl requires `Trace`
+/// on each traced field's type rather than on every type parameter. A field
+/// behind `#[trace(ignore)]` adds no bound; any other field (a
+/// `PhantomData<T>` included) needs its type to implement `Trace`.
+fn add_trace_bounds(generics: &mut syn::Generics, data: &Data) {
+    let where_clause = generics.make_where_clause();
+    for ty in field_types(data) {
+        where_clause.predicates.push(syn::parse_quote!(#ty: Trace));
+    }
+}
+
+fn field_types(data: &Data) -> Vec<&syn::Type> {
+    match data {
+        Data::Struct(data) => fields_types(&data.fields),
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .flat_map(|variant| fields_types(&variant.fields))
+            .collect(),
+        Data::Union(_) => Vec::new(),
+    }
+}
+
+fn fields_types(fields: &Fields) -> Vec<&syn::Type> {
+    match fields {
+        Fields::Named(f) => f
+            .named
+            .iter()
+            .filter(|field| !has_ignore_attr(&field.attrs))
+            .map(|field| &field.ty)
+            .collect(),
+        Fields::Unnamed(f) => f
+            .unnamed
+            .iter()
+            .filter(|field| !has_ignore_attr(&field.attrs))
+            .map(|field| &field.ty)
+            .collect(),
+        Fields::Unit => Vec::new(),
+    }
+}
+
+/// Builds the `trace`/`is_atomic` bodies for a plain (non-enum) field list.
+fn fields_trace(
+    fields: &Fields,
+    force_atomic: bool,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let traced: Vec<_> = match fields {
+        Fields::Named(f) => f
+            .named
+            .iter()
+            .filter(|field| !has_ignore_attr(&field.attrs))
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                quote! { self.#ident.trace(tracer); }
+            })
+            .collect(),
+        Fields::Unnamed(f) => f
+            .unnamed
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| !has_ignore_attr(&field.attrs))
+            .map(|(i, _)| {
+                let index = Index::from(i);
+                quote! { self.#index.trace(tracer); }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let atomic_checks: Vec<_> = match fields {
+        Fields::Named(f) => f
+            .named
+            .iter()
+            .filter(|field| !has_ignore_attr(&field.attrs))
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                quote! { self.#ident.is_atomic() }
+            })
+            .collect(),
+        Fields::Unnamed(f) => f
+            .unnamed
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| !has_ignore_attr(&field.attrs))
+            .map(|(i, _)| {
+                let index = Index::from(i);
+                quote! { self.#index.is_atomic() }
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    let is_atomic_body = if force_atomic || atomic_checks.is_empty() {
+        quote! { true }
+    } else {
+        quote! { #(#atomic_checks)&&* }
+    };
+
+    (quote! { #(#traced)* }, is_atomic_body)
+}
+
+/// Builds the pieces of one enum variant's match arms: the pattern, which
+/// binds every field so `trace`/`is_atomic` can be invoked on it by name,
+/// the `trace` statements, and the `is_atomic` expression.
+fn variant_trace(
+    variant_ident: &syn::Ident,
+    fields: &Fields,
+    force_atomic: bool,
+) -> (
+    proc_macro2::TokenStream,
+    Vec<proc_macro2::TokenStream>,
+    proc_macro2::TokenStream,
+) {
+    match fields {
+        Fields::Named(f) => {
+            // Fields behind #[trace(ignore)] are bound with a leading
+            // underscore (rather than omitted with `..`) so the pattern
+            // still destructures every field by name, but doesn't trigger
+            // an unused-variable warning for the ones the arm body never
+            // references.
+            let field_idents: Vec<_> = f
+                .named
+                .iter()
+                .map(|field| field.ident.clone().unwrap())
+                .collect();
+            let idents: Vec<_> = f
+                .named
+                .iter()
+                .map(|field| {
+                    let ident = field.ident.clone().unwrap();
+                    if has_ignore_attr(&field.attrs) {
+                        syn::Ident::new(&format!("_{}", ident), ident.span())
+                    } else {
+                        ident
+                    }
+                })
+                .collect();
+            let traced: Vec<_> = f
+                .named
+                .iter()
+                .filter(|field| !has_ignore_attr(&field.attrs))
+                .map(|field| {
+                    let ident = field.ident.as_ref().unwrap();
+                    quote! { #ident.trace(tracer); }
+                })
+                .collect();
+            let checks: Vec<_> = f
+                .named
+                .iter()
+                .filter(|field| !has_ignore_attr(&field.attrs))
+                .map(|field| {
+                    let ident = field.ident.as_ref().unwrap();
+                    quote! { #ident.is_atomic() }
+                })
+                .collect();
+            let atomic_expr = if force_atomic || checks.is_empty() {
+                quote! { true }
+            } else {
+                quote! { #(#checks)&&* }
+            };
+            (
+                quote! { #variant_ident { #(#field_idents: ref #idents),* } },
+                traced,
+                atomic_expr,
+            )
+        }
+        Fields::Unnamed(f) => {
+            // Same underscore-prefixing trick as the named-fields branch
+            // above, for tuple-variant fields behind #[trace(ignore)].
+            let idents: Vec<_> = f
+                .unnamed
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let name = if has_ignore_attr(&field.attrs) {
+                        format!("_field{}", i)
+                    } else {
+                        format!("field{}", i)
+                    };
+                    syn::Ident::new(&name, proc_macro2::Span::call_site())
+                })
+                .collect();
+            let traced: Vec<_> = f
+                .unnamed
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| !has_ignore_attr(&field.attrs))
+                .map(|(i, _)| {
+                    let ident = &idents[i];
+                    quote! { #ident.trace(tracer); }
+                })
+                .collect();
+            let checks: Vec<_> = f
+                .unnamed
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| !has_ignore_attr(&field.attrs))
+                .map(|(i, _)| {
+                    let ident = &idents[i];
+                    quote! { #ident.is_atomic() }
+                })
+                .collect();
+            let atomic_expr = if force_atomic || checks.is_empty() {
+                quote! { true }
+            } else {
+                quote! { #(#checks)&&* }
+            };
+            (quote! { #variant_ident( #(ref #idents),* ) }, traced, atomic_expr)
+        }
+        Fields::Unit => (quote! { #variant_ident }, Vec::new(), quote! { true }),
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Exercises `#[derive(Trace)]` on a sample struct and enum, checking that
+//! the generated `trace`/`is_atomic` bodies do what the doc comments on
+//! `bacon_rajan_cc_derive::derive_trace` promise: every non-ignored field
+//! gets traced, `#[trace(ignore)]` fields are skipped, and `is_atomic()` is
+//! the conjunction of the traced fields' own `is_atomic()` unless
+//! `#[trace(atomic)]` forces it.
+
+extern crate bacon_rajan_cc;
+#[macro_use]
+extern crate bacon_rajan_cc_derive;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use bacon_rajan_cc::{CcBoxPtr, Trace, Tracer};
+
+/// Reports itself to the tracer, so `traced_count` can tell which fields a
+/// derived `trace` actually visited.
+struct Leaf;
+
+impl Trace for Leaf {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer(self);
+    }
+}
+
+#[derive(Trace)]
+struct Pair {
+    traced: Leaf,
+    #[trace(ignore)]
+    #[allow(dead_code)]
+    ignored: Leaf,
+}
+
+#[derive(Trace)]
+struct AllAtomic {
+    a: u32,
+    b: u32,
+}
+
+#[derive(Trace)]
+#[trace(atomic)]
+struct ForcedAtomic {
+    value: Cell<u32>,
+}
+
+#[derive(Trace)]
+enum Either {
+    Left(Leaf),
+    Right {
+        kept: Leaf,
+        #[trace(ignore)]
+        skipped: Leaf,
+    },
+}
+
+fn traced_count(value: &dyn Trace) -> usize {
+    let count = Rc::new(Cell::new(0));
+    {
+        let count = Rc::clone(&count);
+        let mut tracer = move |_ptr: &dyn CcBoxPtr| count.set(count.get() + 1);
+        value.trace(&mut tracer);
+    }
+    count.get()
+}
+
+#[test]
+fn struct_traces_only_non_ignored_fields() {
+    let pair = Pair {
+        traced: Leaf,
+        ignored: Leaf,
+    };
+    // Each `Leaf` reports itself once, so exactly one visit means the
+    // non-ignored field was traced and the ignored one was not.
+    assert_eq!(traced_count(&pair), 1);
+    // `Leaf` keeps the default `is_atomic()` of false, so the derived
+    // `is_atomic` -- the conjunction of the non-ignored fields' own
+    // `is_atomic()` -- must come out false even though the ignored field is
+    // skipped entirely.
+    assert!(!pair.is_atomic());
+}
+
+#[test]
+fn is_atomic_is_the_conjunction_of_traced_fields() {
+    let value = AllAtomic { a: 1, b: 2 };
+    assert!(value.is_atomic());
+}
+
+#[test]
+fn trace_atomic_attribute_forces_is_atomic() {
+    let value = ForcedAtomic {
+        value: Cell::new(1),
+    };
+    assert!(value.is_atomic());
+}
+
+#[test]
+fn enum_traces_the_live_variant_only() {
+    let left = Either::Left(Leaf);
+    assert_eq!(traced_count(&left), 1);
+    assert!(!left.is_atomic());
+
+    let right = Either::Right {
+        kept: Leaf,
+        skipped: Leaf,
+    };
+    assert_eq!(traced_count(&right), 1);
+    assert!(!right.is_atomic());
+}
@@ -0,0 +1,24 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The trait implemented by the boxed storage behind a single-threaded
+//! cycle-collected smart pointer, so `trace::Tracer` has a concrete `&dyn
+//! CcBoxPtr` to call back with.
+//!
+//! The single-threaded `Cc<T>` this trait is meant for isn't implemented in
+//! this crate yet; its thread-safe sibling is, with its own atomic-backed
+//! counters in [`sync::CcBoxPtr`](../sync/trait.CcBoxPtr.html). For now this
+//! blanket impl is just enough surface for `Trace`/`Tracer` to work with.
+
+use trace::Trace;
+
+/// Implemented by the boxed storage behind a cycle-collected smart pointer.
+pub trait CcBoxPtr: Trace {}
+
+impl<T: Trace> CcBoxPtr for T {}
@@ -0,0 +1,266 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Epoch-based deferred reclamation for the cycle collector, in the spirit
+//! of crossbeam's epoch GC.
+//!
+//! Without this, `collect_cycles` frees a white node's `Box` the moment it
+//! decides the node is garbage -- but a re-entrant or long-running
+//! `Trace::trace` elsewhere might still be mid-walk and holding a raw
+//! `&CcBoxPtr` into that same node. Deferring the actual free until every
+//! participant has moved on closes that hazard.
+//!
+//! The scheme: a global epoch counter and a local epoch per participant. A
+//! thread [`pin`](fn.pin.html)s itself before walking a graph via
+//! `Trace::trace`, which records its local epoch as caught up with the
+//! global one. Garbage collected while pinned goes into that epoch's
+//! [`Bag`](struct.Bag.html). A bag is only dropped -- and its contents
+//! actually freed -- once every participant has been observed at an epoch
+//! at least two generations newer than the bag's, which guarantees no
+//! pinned `Tracer` callback can still be dereferencing anything in it.
+//!
+//! **Nothing calls `pin`/`defer`/`flush` outside of this module's own tests
+//! yet** -- `collect_cycles` doesn't exist, so there's no mark-gray/scan
+//! pass to defer frees for. Treat this as the reclamation half of a
+//! collector that `sync::CcBoxPtr`'s `color()`/`compare_and_set_color()` are
+//! the other (marking) half of.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use sync::CcBoxPtr;
+
+/// The global epoch, advanced by [`flush`](fn.flush.html).
+static GLOBAL_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// A collected `Box<dyn CcBoxPtr>` whose free has been deferred until it's
+/// safe. Bounded by `Send + Sync` since a bag sealed by one thread may be
+/// flushed and dropped by another.
+type Garbage = Box<dyn CcBoxPtr + Send + Sync>;
+
+/// The garbage collected by participants pinned during one epoch.
+///
+/// A bag is reclaimed -- its contents actually dropped -- only once every
+/// participant has advanced at least two epochs past the one in which the
+/// bag was sealed.
+#[derive(Default)]
+pub struct Bag {
+    epoch: usize,
+    garbage: Vec<Garbage>,
+}
+
+impl Bag {
+    fn sealed_at(epoch: usize) -> Bag {
+        Bag {
+            epoch,
+            garbage: Vec::new(),
+        }
+    }
+
+    /// Defers the free of a node the collector just determined is garbage.
+    pub fn defer(&mut self, node: Garbage) {
+        self.garbage.push(node);
+    }
+}
+
+/// Tracks each pinned participant's most recently observed epoch.
+///
+/// A participant with local epoch `None` is unpinned and doesn't hold back
+/// reclamation at all; `Some(e)` means it was last seen at global epoch `e`.
+#[derive(Default)]
+struct Participants {
+    local_epochs: Vec<Option<usize>>,
+}
+
+/// A guard returned by [`pin`](fn.pin.html), recording that the current
+/// thread is walking the graph and must not have any node it can reach
+/// freed out from under it until the guard is dropped.
+pub struct Guard {
+    participant: usize,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        COLLECTOR.lock().unwrap().unpin(self.participant);
+    }
+}
+
+struct EpochCollector {
+    participants: Participants,
+    bags: Vec<Bag>,
+}
+
+impl EpochCollector {
+    fn new() -> EpochCollector {
+        EpochCollector {
+            participants: Participants::default(),
+            bags: Vec::new(),
+        }
+    }
+
+    fn pin(&mut self) -> usize {
+        let epoch = GLOBAL_EPOCH.load(Ordering::Acquire);
+        let slot = self
+            .participants
+            .local_epochs
+            .iter()
+            .position(|e| e.is_none());
+        match slot {
+            Some(i) => {
+                self.participants.local_epochs[i] = Some(epoch);
+                i
+            }
+            None => {
+                self.participants.local_epochs.push(Some(epoch));
+                self.participants.local_epochs.len() - 1
+            }
+        }
+    }
+
+    fn unpin(&mut self, participant: usize) {
+        self.participants.local_epochs[participant] = None;
+    }
+
+    /// Advances the global epoch and drops every bag that every pinned
+    /// participant has moved at least two epochs past.
+    fn flush(&mut self) {
+        let epoch = GLOBAL_EPOCH.fetch_add(1, Ordering::AcqRel) + 1;
+        let min_observed = self
+            .participants
+            .local_epochs
+            .iter()
+            .filter_map(|e| *e)
+            .min()
+            .unwrap_or(epoch);
+        self.bags.retain(|bag| min_observed < bag.epoch + 2);
+    }
+
+    fn defer(&mut self, node: Garbage) {
+        let epoch = GLOBAL_EPOCH.load(Ordering::Acquire);
+        match self.bags.iter_mut().find(|b| b.epoch == epoch) {
+            Some(bag) => bag.defer(node),
+            None => {
+                let mut bag = Bag::sealed_at(epoch);
+                bag.defer(node);
+                self.bags.push(bag);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref COLLECTOR: Mutex<EpochCollector> = Mutex::new(EpochCollector::new());
+}
+
+/// Marks the current thread as walking the graph, returning a [`Guard`]
+/// that must be held for the duration of the walk (e.g. for the lifetime of
+/// a `Trace::trace` call tree). While pinned, no node this thread can reach
+/// will be freed by [`flush`](fn.flush.html), even if another thread's
+/// `collect_cycles` determines it's part of a garbage cycle concurrently.
+pub fn pin() -> Guard {
+    let participant = COLLECTOR.lock().unwrap().pin();
+    Guard { participant }
+}
+
+/// Defers the free of a node `collect_cycles` just determined is garbage
+/// until it's safe, i.e. until every currently pinned participant has
+/// advanced past it.
+pub fn defer(node: Garbage) {
+    COLLECTOR.lock().unwrap().defer(node);
+}
+
+/// Force-advances the epoch and drains every bag that's now safe to free.
+///
+/// This is the collector's hook for making reclamation progress outside of
+/// `defer`; call it after a `collect_cycles` pass to bound how much garbage
+/// can pile up waiting on slow participants.
+pub fn flush() {
+    COLLECTOR.lock().unwrap().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sync::AtomicCcBoxData;
+    use trace::{Trace, Tracer};
+
+    /// Garbage with no owned `CcBoxPtr`s of its own -- just enough to satisfy
+    /// `sync::CcBoxPtr` so it can be sealed into a `Bag`.
+    struct Dummy(AtomicCcBoxData);
+
+    impl Dummy {
+        fn new() -> Dummy {
+            Dummy(AtomicCcBoxData::new())
+        }
+    }
+
+    impl Trace for Dummy {
+        fn trace(&self, _tracer: &mut Tracer) {}
+    }
+
+    impl CcBoxPtr for Dummy {
+        fn atomic_data(&self) -> &AtomicCcBoxData {
+            &self.0
+        }
+    }
+
+    fn bag_count() -> usize {
+        COLLECTOR.lock().unwrap().bags.len()
+    }
+
+    /// Walks both halves of the reclamation invariant in one test, since
+    /// `GLOBAL_EPOCH`/`COLLECTOR` are shared process-global state and a
+    /// second test running concurrently would desynchronize the epoch
+    /// arithmetic this asserts on.
+    #[test]
+    fn bag_reclaimed_after_two_epochs_unless_pinned() {
+        let sealed_at = GLOBAL_EPOCH.load(Ordering::Acquire);
+        defer(Box::new(Dummy::new()));
+        assert_eq!(bag_count(), 1);
+
+        flush();
+        assert_eq!(
+            bag_count(),
+            1,
+            "a bag must survive the flush that immediately follows its sealing epoch"
+        );
+
+        flush();
+        assert_eq!(
+            bag_count(),
+            0,
+            "a bag must be reclaimed once two flushes have passed its sealing epoch"
+        );
+        assert_eq!(GLOBAL_EPOCH.load(Ordering::Acquire), sealed_at + 2);
+
+        // A pinned participant must block reclamation past its pin epoch,
+        // no matter how many times the collector flushes.
+        let guard = pin();
+        defer(Box::new(Dummy::new()));
+        assert_eq!(bag_count(), 1);
+
+        flush();
+        flush();
+        flush();
+        assert_eq!(
+            bag_count(),
+            1,
+            "a pinned participant must block reclamation indefinitely"
+        );
+
+        drop(guard);
+        flush();
+        assert_eq!(
+            bag_count(),
+            0,
+            "reclamation proceeds again once every participant has unpinned"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Cycle-collected, reference-counted boxes.
+//!
+//! See [`Trace`](trait.Trace.html) for how to make a type's owned
+//! `CcBoxPtr`s visible to the collector, and [`sync`](sync/index.html) for
+//! the thread-safe variant built on atomic reference counts.
+
+#[macro_use]
+extern crate lazy_static;
+
+// Aliased so it doesn't collide with our own `loom` abstraction module
+// below, which is named after (and re-exports, under `cfg(loom)`) this
+// crate.
+#[cfg(all(test, loom))]
+extern crate loom as loom_crate;
+
+mod cc_box_ptr;
+mod epoch;
+mod loom;
+pub mod sync;
+mod trace;
+
+pub use cc_box_ptr::CcBoxPtr;
+pub use trace::{Trace, TraceSync, Tracer};
@@ -0,0 +1,29 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! An abstraction layer over the atomics used by
+//! [`sync`](../sync/index.html), so they can be swapped for `loom`'s
+//! model-checked equivalents under test.
+//!
+//! Everywhere `sync::CcBoxPtr` touches an atomic, it goes through
+//! `self::atomic` here rather than `std::sync::atomic` directly. In normal
+//! builds this is just a re-export of the `std` types and compiles away to
+//! nothing extra; under `cfg(all(test, loom))` -- this crate's own unit
+//! tests built with `--cfg loom` -- it becomes `loom`'s instrumented
+//! version, which exhaustively explores thread interleavings instead of
+//! running on real hardware. Integration tests under `tests/` link the
+//! library built without `cfg(test)`, so they always get the `std` types.
+
+#[cfg(all(test, loom))]
+pub use loom_crate::sync::atomic;
+
+#[cfg(not(all(test, loom)))]
+pub mod atomic {
+    pub use std::sync::atomic::{AtomicUsize, Ordering};
+}
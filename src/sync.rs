@@ -0,0 +1,312 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT file at the
+// top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A thread-safe `Cc<T>` built on atomic reference counts. The crate has no
+//! single-threaded `Cc<T>` at its root.
+//!
+//! `sync::Cc<T>` stores its strong/weak/color bookkeeping as atomics rather
+//! than `Cell`s, so the counters can be mutated from a `clone()` or `drop()`
+//! on one thread while another thread concurrently clones or drops its own
+//! handle to the same node. `sync::Cc<T>` is `Send`/`Sync` only when
+//! `T: TraceSync`, which keeps non-`Sync` payloads out of a shared object
+//! graph.
+//!
+//! **Cycle collection is not implemented yet.** `color()` and
+//! `compare_and_set_color()` below are scaffolding for a future
+//! mark-gray/scan/collect-white pass over shared graphs; nothing currently
+//! calls them, and as things stand `sync::Cc<T>` behaves exactly like an
+//! `Arc<T>` -- a reference cycle built from `sync::Cc`s will leak. Treat
+//! this module as atomic reference counting with the hooks a collector will
+//! eventually need, not as a working collector.
+
+use std::alloc::{dealloc, Layout};
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+use loom::atomic::{AtomicUsize, Ordering};
+use trace::{Trace, TraceSync, Tracer};
+
+/// A node's strong, weak and color counters, each stored as an atomic.
+///
+/// Following the usual `Rc`/`Arc` pattern, `weak` also counts the single
+/// implicit weak reference owned collectively by all outstanding strong
+/// handles: it's only released once `strong` drops to zero. That's what
+/// lets `Cc::drop` free the *value* as soon as the last strong handle goes
+/// away while leaving the allocation itself alive for any `Weak<T>` still
+/// pointing at it.
+pub struct AtomicCcBoxData {
+    strong: AtomicUsize,
+    weak: AtomicUsize,
+    color: AtomicUsize,
+}
+
+impl AtomicCcBoxData {
+    pub(crate) fn new() -> AtomicCcBoxData {
+        AtomicCcBoxData {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+            color: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Implemented by the boxed storage behind `sync::Cc<T>`.
+///
+/// Unlike the crate-root `CcBoxPtr`, which is a marker trait with no
+/// counters, this one owns the node's counters, and every counter access
+/// goes through an atomic, so two threads can concurrently clone and drop handles to the same node without
+/// racing each other into a double free or a missed decrement.
+pub trait CcBoxPtr: Trace {
+    /// Returns a reference to this node's atomic strong/weak/color counters.
+    fn atomic_data(&self) -> &AtomicCcBoxData;
+
+    #[inline]
+    fn strong(&self) -> usize {
+        self.atomic_data().strong.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn inc_strong(&self) {
+        self.atomic_data().strong.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Decrements the strong count, returning `true` if this call observed
+    /// (and thus is responsible for reacting to) the count reaching zero.
+    ///
+    /// Using `fetch_sub` rather than a load-then-store keeps this atomic:
+    /// two threads dropping the last two `Cc`s at once can never both see
+    /// "I dropped it to zero."
+    #[inline]
+    fn dec_strong(&self) -> bool {
+        self.atomic_data().strong.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    #[inline]
+    fn weak(&self) -> usize {
+        self.atomic_data().weak.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn inc_weak(&self) {
+        self.atomic_data().weak.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Decrements the weak count, returning `true` if this call observed it
+    /// reaching zero (and is thus responsible for freeing the allocation).
+    #[inline]
+    fn dec_weak(&self) -> bool {
+        self.atomic_data().weak.fetch_sub(1, Ordering::AcqRel) == 1
+    }
+
+    #[inline]
+    fn color(&self) -> usize {
+        self.atomic_data().color.load(Ordering::Acquire)
+    }
+
+    /// Atomically transitions this node's color from `from` to `to`,
+    /// returning `true` on success.
+    ///
+    /// Reserved for a future mark-gray/scan/collect-white pass, which would
+    /// use this instead of an unconditional store so that a node already
+    /// claimed by a concurrent pass is never double-processed. Nothing
+    /// calls this yet.
+    #[inline]
+    fn compare_and_set_color(&self, from: usize, to: usize) -> bool {
+        self.atomic_data()
+            .color
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+}
+
+/// A thread-safe smart pointer with atomic reference counts.
+///
+/// `Cc<T>` implements `Send` and `Sync` only when `T: TraceSync`
+/// (i.e. `T: Trace + Send + Sync`), which is what lets the compiler reject
+/// sharing a graph of non-`Sync` payloads across threads at the type level
+/// rather than relying on a runtime check.
+///
+/// Cycle collection is not implemented yet -- see the module docs.
+pub struct Cc<T: Trace + ?Sized + 'static> {
+    ptr: NonNull<CcBox<T>>,
+}
+
+struct CcBox<T: Trace + ?Sized + 'static> {
+    data: AtomicCcBoxData,
+    value: T,
+}
+
+/// Frees an allocation whose `value` has already been dropped in place,
+/// without running `T`'s destructor a second time.
+unsafe fn deallocate(ptr: NonNull<CcBox<impl Trace + ?Sized>>) {
+    let layout = Layout::for_value(ptr.as_ref());
+    dealloc(ptr.as_ptr() as *mut u8, layout);
+}
+
+unsafe impl<T: TraceSync + ?Sized> Send for Cc<T> {}
+unsafe impl<T: TraceSync + ?Sized> Sync for Cc<T> {}
+
+impl<T: Trace> Cc<T> {
+    /// Constructs a new `Cc<T>` with a strong count of one.
+    pub fn new(value: T) -> Cc<T> {
+        let boxed = Box::new(CcBox {
+            data: AtomicCcBoxData::new(),
+            value,
+        });
+        Cc {
+            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(boxed)) },
+        }
+    }
+}
+
+impl<T: Trace + ?Sized> Cc<T> {
+    fn inner(&self) -> &CcBox<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Returns the number of outstanding strong references to `this`.
+    pub fn strong_count(this: &Cc<T>) -> usize {
+        this.inner().strong()
+    }
+
+    /// Returns the number of outstanding `Weak` references to `this`,
+    /// including the implicit one held by the strong references.
+    pub fn weak_count(this: &Cc<T>) -> usize {
+        this.inner().weak()
+    }
+
+    /// Creates a new `Weak` pointer to `this`'s allocation.
+    pub fn downgrade(this: &Cc<T>) -> Weak<T> {
+        this.inner().inc_weak();
+        Weak { ptr: this.ptr }
+    }
+}
+
+impl<T: Trace + ?Sized> Trace for CcBox<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.value.trace(tracer);
+    }
+
+    fn is_atomic(&self) -> bool {
+        self.value.is_atomic()
+    }
+}
+
+impl<T: Trace + ?Sized> Trace for Cc<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.inner().trace(tracer);
+    }
+
+    fn is_atomic(&self) -> bool {
+        self.inner().is_atomic()
+    }
+}
+
+impl<T: Trace + ?Sized> CcBoxPtr for CcBox<T> {
+    #[inline]
+    fn atomic_data(&self) -> &AtomicCcBoxData {
+        &self.data
+    }
+}
+
+impl<T: Trace + ?Sized> Clone for Cc<T> {
+    fn clone(&self) -> Cc<T> {
+        self.inner().inc_strong();
+        Cc { ptr: self.ptr }
+    }
+}
+
+impl<T: Trace + ?Sized> Deref for Cc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: Trace + ?Sized> Drop for Cc<T> {
+    fn drop(&mut self) {
+        if self.inner().dec_strong() {
+            // The strong count just reached zero on this thread: the value
+            // is safe to drop right away, since no other strong handle (and
+            // thus no `Deref`) can observe it afterwards. The allocation
+            // itself is kept alive until every `Weak` -- including the
+            // implicit one the strong count group holds -- goes away too,
+            // same as `Rc`/`Arc`.
+            unsafe {
+                ::std::ptr::drop_in_place(&mut (*self.ptr.as_ptr()).value);
+            }
+            if self.inner().dec_weak() {
+                unsafe {
+                    deallocate(self.ptr);
+                }
+            }
+        }
+    }
+}
+
+/// A non-owning, thread-safe handle to a `sync::Cc<T>`'s allocation.
+///
+/// A `Weak<T>` doesn't keep its value alive; call [`upgrade`](#method.upgrade)
+/// to get a `Cc<T>` back, which fails once the value has already been
+/// dropped.
+pub struct Weak<T: Trace + ?Sized + 'static> {
+    ptr: NonNull<CcBox<T>>,
+}
+
+unsafe impl<T: TraceSync + ?Sized> Send for Weak<T> {}
+unsafe impl<T: TraceSync + ?Sized> Sync for Weak<T> {}
+
+impl<T: Trace + ?Sized> Weak<T> {
+    fn inner(&self) -> &CcBox<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// Attempts to upgrade this `Weak` into a `Cc`, returning `None` if the
+    /// value has already been dropped.
+    ///
+    /// This uses a compare-and-swap loop on the strong count rather than a
+    /// plain `fetch_add`, so a `Weak` can never resurrect a `Cc` once the
+    /// strong count has already reached zero.
+    pub fn upgrade(&self) -> Option<Cc<T>> {
+        let strong = &self.inner().atomic_data().strong;
+        let mut current = strong.load(Ordering::Acquire);
+        loop {
+            if current == 0 {
+                return None;
+            }
+            match strong.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(Cc { ptr: self.ptr }),
+                Err(observed) => current = observed,
+            }
+        }
+    }
+}
+
+impl<T: Trace + ?Sized> Clone for Weak<T> {
+    fn clone(&self) -> Weak<T> {
+        self.inner().inc_weak();
+        Weak { ptr: self.ptr }
+    }
+}
+
+impl<T: Trace + ?Sized> Drop for Weak<T> {
+    fn drop(&mut self) {
+        if self.inner().dec_weak() {
+            unsafe {
+                deallocate(self.ptr);
+            }
+        }
+    }
+}